@@ -3,21 +3,92 @@ use std::env::args;
 use std::fs::File;
 use std::io::{self, BufRead, BufWriter, Write};
 use std::path::Path;
+use std::sync::Arc;
 
-type Dictionary = HashMap<Vec<u8>, Vec<String>>;
+/// Maps the digit key of a word to the words sharing it.
+///
+/// Digit sequences are packed into a single integer key rather than a freshly
+/// allocated `Vec<u8>` slice: a sequence `d0 d1 ... dk` becomes
+/// `((1*10 + d0)*10 + d1)...*10 + dk`, i.e. the fold is seeded with a leading
+/// `1` so that sequences differing only in leading zeros never collide. This
+/// lets the search extend a prefix by one digit with a single `acc*10 + digit`
+/// instead of allocating and hashing a new slice on every probe. The rare word
+/// whose digit sequence overflows a `u128` (more than ~38 digits) falls back to
+/// a `Vec<u8>` key in `long`.
+struct Dictionary {
+    packed: HashMap<u128, Vec<String>>,
+    long: HashMap<Vec<u8>, Vec<String>>,
+    /// Longest digit key seen while loading the dictionary.
+    max_key_len: usize,
+    /// Line order (0-based) of the cheapest word sharing each key, used as a
+    /// frequency-rank proxy by the "most natural" translation mode.
+    packed_rank: HashMap<u128, usize>,
+    long_rank: HashMap<Vec<u8>, usize>,
+    /// Total number of words loaded (`N` in the cost function).
+    word_count: usize,
+}
+
+/// The packed or overflowing digit key of a single word.
+enum Key {
+    Packed(u128),
+    Long(Vec<u8>),
+}
 
 struct Cons<'a, T: 'a> {
     data: T,
     next: Option<&'a Cons<'a, T>>,
 }
 
+/// Sink for completed translations of a number.
+///
+/// The search in `find_translations` is identical in both modes; only what
+/// happens once a full encoding is found differs: the printing mode renders it
+/// to a writer, while the counting mode just bumps a tally so the benchmark
+/// measures the search rather than the formatting and I/O.
+trait Solutions {
+    fn emit(&mut self, num: &str, words: Option<&Cons<&str>>) -> io::Result<()>;
+}
+
+/// Renders each translation as `num: word word ...`.
+struct Print<W: Write>(W);
+
+impl<W: Write> Solutions for Print<W> {
+    fn emit(&mut self, num: &str, words: Option<&Cons<&str>>) -> io::Result<()> {
+        write!(self.0, "{}:", num)?;
+        write_reversed(&mut self.0, words)?;
+        writeln!(self.0)
+    }
+}
+
+/// Counts translations instead of rendering them.
+struct Count(u64);
+
+impl Solutions for Count {
+    fn emit(&mut self, _num: &str, _words: Option<&Cons<&str>>) -> io::Result<()> {
+        self.0 += 1;
+        Ok(())
+    }
+}
+
 /// Port of Peter Norvig's Lisp solution to the Prechelt phone-encoding problem.
 ///
 /// Even though this is intended as a port, it deviates quite a bit from it
 /// due to the very different natures of Lisp and Rust.
 fn main() -> io::Result<()> {
-    // drop itself from args
-    let mut args = args().skip(1);
+    // In count mode we only tally how many encodings each number has and print
+    // a single final total, skipping all per-solution formatting and I/O. It is
+    // selected by the `--count` flag or the `COUNT` environment variable.
+    let all_args: Vec<String> = args().skip(1).collect();
+    let count_only =
+        std::env::var_os("COUNT").is_some() || all_args.iter().any(|arg| arg == "--count");
+    // In best mode we emit only the single most natural translation of each
+    // number, ranked by word commonality. Selected by `--best` or `BEST`.
+    let best_only =
+        std::env::var_os("BEST").is_some() || all_args.iter().any(|arg| arg == "--best");
+    // drop the recognised flags, leaving only positional file arguments
+    let mut args = all_args
+        .into_iter()
+        .filter(|arg| arg != "--count" && arg != "--best");
     let words_file: String = args.next().unwrap_or("tests/words.txt".into());
     let input_file: String = args.next().unwrap_or("tests/numbers.txt".into());
 
@@ -28,37 +99,104 @@ fn main() -> io::Result<()> {
     let lock = stdout.lock();
     let mut buf = BufWriter::new(lock);
 
-    for line in read_lines(input_file)? {
-        if let Ok(num) = line {
-            let digits: Vec<_> = num.chars()
-                .filter_map(numeric_char_to_digit)
-                .collect();
-            write_translations(&mut buf, &dict, &num, &digits, None)?;
+    if count_only {
+        let mut solutions = Count(0);
+        for line in read_lines(input_file)? {
+            if let Ok(num) = line {
+                let digits: Vec<_> = num.chars()
+                    .filter_map(numeric_char_to_digit)
+                    .collect();
+                find_translations(&mut solutions, &dict, &num, &digits, None)?;
+            }
+        }
+        writeln!(buf, "{}", solutions.0)?;
+    } else if best_only {
+        for line in read_lines(input_file)? {
+            if let Ok(num) = line {
+                let digits: Vec<_> = num.chars()
+                    .filter_map(numeric_char_to_digit)
+                    .collect();
+                write_best_translation(&mut buf, &dict, &num, &digits)?;
+            }
+        }
+    } else {
+        // The dictionary is read-only once loaded, so share it across a worker
+        // pool and let each worker render a contiguous shard of the input into
+        // its own buffer. Buffers are flushed in input order, so the output is
+        // byte-for-byte identical to the single-threaded run.
+        let lines: Vec<String> = read_lines(input_file)?.flatten().collect();
+        let dict = Arc::new(dict);
+        let workers = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(lines.len().max(1));
+        let chunk = ((lines.len() + workers - 1) / workers).max(1);
+        let buffers = std::thread::scope(|scope| -> io::Result<Vec<Vec<u8>>> {
+            let mut handles = Vec::new();
+            for shard in lines.chunks(chunk) {
+                let dict = Arc::clone(&dict);
+                handles.push(scope.spawn(move || render_lines(&dict, shard)));
+            }
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        })?;
+        for shard_buf in buffers {
+            buf.write_all(&shard_buf)?;
         }
     }
     Ok(())
 }
 
-fn write_translations<'dict, W: Write>(
-    writer: &mut W,
+/// Renders every translation of each number in `lines` into a fresh buffer, so
+/// a worker thread can build its shard of the output independently.
+fn render_lines(dict: &Dictionary, lines: &[String]) -> io::Result<Vec<u8>> {
+    let mut solutions = Print(Vec::new());
+    for num in lines {
+        let digits: Vec<u8> = num.chars().filter_map(numeric_char_to_digit).collect();
+        find_translations(&mut solutions, dict, num, &digits, None)?;
+    }
+    Ok(solutions.0)
+}
+
+fn find_translations<'dict, S: Solutions>(
+    solutions: &mut S,
     dict: &'dict Dictionary,
     num: &str,
     digits: &[u8],
     words: Option<&Cons<&'dict str>>,
 ) -> io::Result<()> {
     if digits.len() == 0 {
-        write!(writer, "{}:", num)?;
-        write_reversed(writer, words)?;
-        writeln!(writer)?;
+        solutions.emit(num, words)?;
     } else {
         let mut found_word = false;
-        for i in 0..digits.len() {
-            let (n, rest) = digits.split_at(i + 1);
-            if let Some(ws) = dict.get(n) {
+        // Carry a running accumulator so extending the probed prefix by one
+        // digit is a single `acc*10 + digit`. Once the prefix can no longer be
+        // packed into a u128 we fall back to slice lookups in `dict.long`.
+        let mut acc: u128 = 1;
+        let mut packable = true;
+        // No dictionary key is longer than `max_key_len`, so there is no point
+        // probing prefixes beyond it.
+        let probe = digits.len().min(dict.max_key_len);
+        for i in 0..probe {
+            let rest = &digits[i + 1..];
+            let matched = if packable {
+                match acc.checked_mul(10).and_then(|a| a.checked_add(digits[i] as u128)) {
+                    Some(next) => {
+                        acc = next;
+                        dict.packed.get(&acc)
+                    }
+                    None => {
+                        packable = false;
+                        dict.long.get(&digits[..i + 1])
+                    }
+                }
+            } else {
+                dict.long.get(&digits[..i + 1])
+            };
+            if let Some(ws) = matched {
                 for word in ws {
                     found_word = true;
-                    write_translations(
-                        writer,
+                    find_translations(
+                        solutions,
                         dict,
                         num,
                         rest,
@@ -75,8 +213,8 @@ fn write_translations<'dict, W: Write>(
                 .map(|c| c.data.chars().all(char::is_numeric))
                 .unwrap_or(false)
         {
-            write_translations(
-                writer,
+            find_translations(
+                solutions,
                 dict,
                 num,
                 &digits[1..],
@@ -90,6 +228,91 @@ fn write_translations<'dict, W: Write>(
     Ok(())
 }
 
+/// Fixed high cost for a bare single-digit filler, chosen well above any word
+/// cost so the search only falls back to a digit when no word fits.
+const FILLER_COST: f64 = 100.0;
+
+impl Dictionary {
+    /// The cheapest (lowest-rank) word sharing a digit key, with its cost
+    /// `ln((rank + 1) * ln(N))`, or `None` if no word matches the key.
+    fn best_match(&self, digits: &[u8]) -> Option<(f64, &str)> {
+        let (words, rank) = match pack_digits(digits) {
+            Some(n) => (self.packed.get(&n)?, *self.packed_rank.get(&n)?),
+            None => (self.long.get(digits)?, *self.long_rank.get(digits)?),
+        };
+        let ln_n = (self.word_count as f64).ln();
+        let cost = (((rank + 1) as f64) * ln_n).ln();
+        Some((cost, words[0].as_str()))
+    }
+}
+
+/// Emits the single most natural translation of `num`, chosen by a
+/// frequency-weighted dynamic program (a Word-Ninja-style shortest path over
+/// digit positions). `best[i]` is the minimum cost to encode the first `i`
+/// digits; a two-way state split enforces the Prechelt rule that two bare
+/// digits never sit next to each other. Numbers with no valid encoding produce
+/// a bare `num:` line.
+fn write_best_translation<W: Write>(
+    writer: &mut W,
+    dict: &Dictionary,
+    num: &str,
+    digits: &[u8],
+) -> io::Result<()> {
+    let n = digits.len();
+    let maxword = dict.max_key_len.max(1);
+    // State 0: the token ending at this position is a word. State 1: it is a
+    // bare digit. Keeping the two apart lets the filler transition require a
+    // non-digit predecessor.
+    let inf = f64::INFINITY;
+    let mut best = vec![[inf; 2]; n + 1];
+    let mut back: Vec<[Option<(usize, usize, String)>; 2]> = Vec::with_capacity(n + 1);
+    for _ in 0..=n {
+        back.push([None, None]);
+    }
+    best[0] = [0.0, 0.0];
+    for i in 1..=n {
+        for k in 1..=maxword.min(i) {
+            if let Some((cost, word)) = dict.best_match(&digits[i - k..i]) {
+                let (prev_state, prev) = if best[i - k][0] <= best[i - k][1] {
+                    (0, best[i - k][0])
+                } else {
+                    (1, best[i - k][1])
+                };
+                if prev + cost < best[i][0] {
+                    best[i][0] = prev + cost;
+                    back[i][0] = Some((i - k, prev_state, word.to_string()));
+                }
+            }
+        }
+        // Single-digit filler, only where no word matches that digit and the
+        // preceding token is a word (never another bare digit).
+        if dict.best_match(&digits[i - 1..i]).is_none() {
+            let prev = best[i - 1][0];
+            if prev + FILLER_COST < best[i][1] {
+                best[i][1] = prev + FILLER_COST;
+                back[i][1] = Some((i - 1, 0, digit_to_str(digits[i - 1]).to_string()));
+            }
+        }
+    }
+    write!(writer, "{}:", num)?;
+    let mut state = if best[n][0] <= best[n][1] { 0 } else { 1 };
+    if best[n][state].is_finite() {
+        let mut tokens = Vec::new();
+        let mut i = n;
+        while i > 0 {
+            let (prev, prev_state, token) =
+                back[i][state].clone().expect("reachable state has a back-pointer");
+            tokens.push(token);
+            i = prev;
+            state = prev_state;
+        }
+        for token in tokens.iter().rev() {
+            write!(writer, " {}", token)?;
+        }
+    }
+    writeln!(writer)
+}
+
 fn write_reversed<W: Write>(writer: &mut W, words: Option<&Cons<&str>>) -> io::Result<()> {
     if let Some(c) = words {
         write_reversed(writer, c.next)?;
@@ -104,18 +327,46 @@ fn digit_to_str(digit: u8) -> &'static str {
 
 fn load_dict<P>(words_file: P) -> io::Result<Dictionary>
 where P: AsRef<Path> {
-    let mut dict = HashMap::with_capacity(100);
+    let mut dict = Dictionary {
+        packed: HashMap::with_capacity(100),
+        long: HashMap::new(),
+        max_key_len: 0,
+        packed_rank: HashMap::new(),
+        long_rank: HashMap::new(),
+        word_count: 0,
+    };
     let words = read_lines(words_file)?;
     for line in words {
         if let Ok(word) = line {
-            let key = word_to_number(&word);
-            let words = dict.entry(key).or_insert_with(|| Vec::new());
-            words.push(word);
+            let rank = dict.word_count;
+            dict.word_count += 1;
+            match word_to_number(&word) {
+                Key::Packed(n) => {
+                    dict.max_key_len = dict.max_key_len.max(packed_len(n));
+                    dict.packed_rank.entry(n).or_insert(rank);
+                    dict.packed.entry(n).or_insert_with(Vec::new).push(word);
+                }
+                Key::Long(digits) => {
+                    dict.max_key_len = dict.max_key_len.max(digits.len());
+                    dict.long_rank.entry(digits.clone()).or_insert(rank);
+                    dict.long.entry(digits).or_insert_with(Vec::new).push(word);
+                }
+            }
         }
     }
     Ok(dict)
 }
 
+/// Number of digits encoded in a packed key (the leading `1` seed excluded).
+fn packed_len(mut key: u128) -> usize {
+    let mut len = 0;
+    while key > 1 {
+        key /= 10;
+        len += 1;
+    }
+    len
+}
+
 // The output is wrapped in a Result to allow matching on errors
 // Returns an Iterator to the Reader of the lines of the file.
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
@@ -124,8 +375,22 @@ where P: AsRef<Path> {
     Ok(io::BufReader::new(file).lines())
 }
 
-fn word_to_number(word: &str) -> Vec<u8> {
-    word.chars().filter_map(alpha_char_to_digit).collect()
+fn word_to_number(word: &str) -> Key {
+    let digits: Vec<u8> = word.chars().filter_map(alpha_char_to_digit).collect();
+    match pack_digits(&digits) {
+        Some(n) => Key::Packed(n),
+        None => Key::Long(digits),
+    }
+}
+
+/// Packs a digit sequence into a leading-`1`-seeded integer, or `None` if it is
+/// too long to fit in a `u128`.
+fn pack_digits(digits: &[u8]) -> Option<u128> {
+    let mut acc: u128 = 1;
+    for &d in digits {
+        acc = acc.checked_mul(10)?.checked_add(d as u128)?;
+    }
+    Some(acc)
 }
 
 fn alpha_char_to_digit(ch: char) -> Option<u8> {